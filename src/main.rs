@@ -1,9 +1,13 @@
 use git2::Repository;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 #[macro_use]
 extern crate lazy_static;
 extern crate crossbeam;
+extern crate handlebars;
+extern crate serde;
+extern crate toml;
 use crossbeam::crossbeam_channel;
 
 fn main() -> Result<(), Box<std::error::Error>> {
@@ -13,29 +17,42 @@ fn main() -> Result<(), Box<std::error::Error>> {
     }
     let current_dir = std::env::current_dir()?;
     let repo = Repository::open(&current_dir)?;
-    let mut revwalk = repo.revwalk()?;
-    let args: Vec<String> = std::env::args().skip(1).collect();
 
-    if args.len() == 0 {
-        let possible_id = find_latest_tag_commit_id(&repo)?;
-        if possible_id.is_none() {
-            println!("no tags found");
-            return Ok(());
-        }
-        revwalk.hide(possible_id.unwrap())?;
-        revwalk.push_head()?;
-    } else {
-        let range = &args[0];
-        if !range.contains("..") {
-            revwalk.push(repo.revparse_single(range)?.id())?;
-        } else {
-            revwalk.push_range(&range[..])?;
+    let mut config_path: Option<std::path::PathBuf> = None;
+    let mut template_path: Option<std::path::PathBuf> = None;
+    let mut component_filter: Option<String> = None;
+    let mut all = false;
+    let mut args: Vec<String> = Vec::new();
+    let mut raw_args = std::env::args().skip(1);
+    while let Some(arg) = raw_args.next() {
+        match &arg[..] {
+            "--config" => config_path = raw_args.next().map(std::path::PathBuf::from),
+            "--template" => template_path = raw_args.next().map(std::path::PathBuf::from),
+            "--component" => component_filter = raw_args.next(),
+            "--all" => all = true,
+            _ => args.push(arg),
         }
     }
 
-    let revs: Vec<git2::Oid> = revwalk.filter_map(|item| item.ok()).collect();
+    let config = load_config(&current_dir, config_path)?;
+    let links = Links::resolve(&config, &repo);
+    let components = ComponentTrie::from_components(&config.components);
+    let template = match template_path {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => DEFAULT_TEMPLATE.to_string(),
+    };
+
+    let revs = if all {
+        collect_release_revs(&repo)?
+    } else {
+        if args.is_empty() && find_latest_tag_commit_id(&repo)?.is_none() {
+            println!("no tags found");
+            return Ok(());
+        }
+        collect_unreleased_revs(&repo, &args)?
+    };
 
-    let (oid_send, oid_receive) = crossbeam_channel::unbounded::<git2::Oid>();
+    let (oid_send, oid_receive) = crossbeam_channel::unbounded::<(git2::Oid, Release)>();
     let (report_send, report_receive) = crossbeam_channel::unbounded::<Report>();
 
     if revs.len() < num {
@@ -45,12 +62,14 @@ fn main() -> Result<(), Box<std::error::Error>> {
         let dir = current_dir.clone();
         let rs = report_send.clone();
         let or = oid_receive.clone();
+        let components = components.clone();
         std::thread::spawn(move || {
             let repo = Repository::open(dir).expect("unable to open repository");
             let w = Worker {
                 oid_receiver: or,
                 repo: repo,
                 report_sender: rs,
+                components: components,
             };
             w.run();
         });
@@ -67,110 +86,472 @@ fn main() -> Result<(), Box<std::error::Error>> {
         aggregator.add_report(report);
     }
 
-    aggregator.print(std::io::stdout())?;
+    if config.components.is_empty() {
+        aggregator.render("", &config, &links, &template, std::io::stdout())?;
+    } else {
+        // One `CHANGELOG.md` per declared component, written at its root. A
+        // `--component` filter restricts the run to a single component.
+        for component in &config.components {
+            if let Some(name) = &component_filter {
+                if &component.name != name {
+                    continue;
+                }
+            }
+            let path = current_dir.join(&component.root).join("CHANGELOG.md");
+            let file = std::fs::File::create(&path)?;
+            aggregator.render(&component.name, &config, &links, &template, file)?;
+        }
+    }
 
     Ok(())
 }
-fn parse_report(raw_input: &str) -> Option<Report> {
-    lazy_static! {
-        static ref SPLITTER: Regex =
-            Regex::new(r"\n(\n|\s+\n)+").expect("unable to parse report regex");
+
+/// Layout of a single changelog section: which commit type it collects and the
+/// title it is rendered under. Sections are emitted in declaration order.
+#[derive(Deserialize)]
+struct Section {
+    #[serde(rename = "type")]
+    commit_type: String,
+    title: String,
+    #[serde(default)]
+    hidden: bool,
+}
+
+/// Changelog configuration, read from `.changelog.toml`. When the file is
+/// absent, or present but without any `[[sections]]`, the built-in default
+/// reproduces the original `feat`/`fix` layout.
+#[derive(Deserialize)]
+struct Config {
+    #[serde(default = "default_sections")]
+    sections: Vec<Section>,
+    /// URL template for issue links, `{id}` is substituted. When unset a
+    /// default is derived from the `origin` remote.
+    #[serde(default)]
+    issue_url: Option<String>,
+    /// URL template for commit links, `{hash}` is substituted. When unset
+    /// a default is derived from the `origin` remote.
+    #[serde(default)]
+    commit_url: Option<String>,
+    /// Monorepo components, each owning a directory subtree. When empty the tool
+    /// emits a single changelog to stdout; otherwise one `CHANGELOG.md` per
+    /// component is written at its root.
+    #[serde(default)]
+    components: Vec<Component>,
+}
+
+/// A monorepo component: a name and the directory root whose commits are
+/// attributed to it.
+#[derive(Deserialize, Clone)]
+struct Component {
+    name: String,
+    root: String,
+}
+
+fn default_sections() -> Vec<Section> {
+    vec![
+        Section {
+            commit_type: "feat".to_string(),
+            title: "Features".to_string(),
+            hidden: false,
+        },
+        Section {
+            commit_type: "fix".to_string(),
+            title: "Fixes".to_string(),
+            hidden: false,
+        },
+    ]
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            sections: default_sections(),
+            issue_url: None,
+            commit_url: None,
+            components: Vec::new(),
+        }
     }
+}
 
-    let mut split = SPLITTER.split(raw_input);
+/// A trie over path segments mapping directory roots to the component that owns
+/// them. Matching a file walks the trie as far as its path allows and returns
+/// the deepest component on the way down, giving longest-prefix resolution so a
+/// nested component wins over an ancestor that also declares one.
+#[derive(Clone, Default)]
+struct ComponentTrie {
+    component: Option<String>,
+    children: BTreeMap<String, ComponentTrie>,
+}
 
-    let raw_head_line = split.next().unwrap_or("");
+impl ComponentTrie {
+    fn from_components(components: &[Component]) -> Self {
+        let mut root = ComponentTrie::default();
+        for component in components {
+            root.insert(&component.root, &component.name);
+        }
+        root
+    }
 
-    let mut headline_parts: Vec<&str> = raw_head_line.split(":").collect();
+    fn insert(&mut self, root: &str, name: &str) {
+        let mut node = self;
+        for segment in root.split('/').filter(|s| !s.is_empty()) {
+            node = node
+                .children
+                .entry(segment.to_string())
+                .or_insert_with(ComponentTrie::default);
+        }
+        node.component = Some(name.to_string());
+    }
 
-    if headline_parts.len() < 2 {
-        return None;
+    fn is_empty(&self) -> bool {
+        self.component.is_none() && self.children.is_empty()
     }
 
-    let raw_context_and_type = headline_parts.remove(0);
+    /// The name of the component owning `path`, i.e. the deepest node on the
+    /// path that declares one, or `None` if the path lies outside every root.
+    fn match_path(&self, path: &str) -> Option<&str> {
+        let mut node = self;
+        let mut found = node.component.as_deref();
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            match node.children.get(segment) {
+                Some(child) => {
+                    node = child;
+                    if let Some(name) = node.component.as_deref() {
+                        found = Some(name);
+                    }
+                }
+                None => break,
+            }
+        }
+        found
+    }
+}
 
-    let mut type_parts: Vec<&str> = raw_context_and_type.split("(").collect();
-    let commit_type = type_parts.remove(0);
+/// Resolved URL templates used to turn issue ids and commit hashes into
+/// Markdown links. An absent template renders the bare id/hash.
+struct Links {
+    issue_url: Option<String>,
+    commit_url: Option<String>,
+}
 
-    let context = if !type_parts.is_empty() {
-        type_parts.remove(0).replace(")", "")
+impl Links {
+    /// Build the link templates, preferring explicit config values and falling
+    /// back to ones derived from the repository's `origin` remote.
+    fn resolve(config: &Config, repo: &git2::Repository) -> Self {
+        let base = derive_base_url(repo);
+        Links {
+            issue_url: config
+                .issue_url
+                .clone()
+                .or_else(|| base.as_ref().map(|b| format!("{}/issues/{{id}}", b))),
+            commit_url: config
+                .commit_url
+                .clone()
+                .or_else(|| base.as_ref().map(|b| format!("{}/commit/{{hash}}", b))),
+        }
+    }
+
+    fn issue(&self, raw: &str) -> String {
+        let id = raw.trim_start_matches('#');
+        match &self.issue_url {
+            Some(template) => format!("[#{}]({})", id, template.replace("{id}", id)),
+            None => format!("#{}", id),
+        }
+    }
+
+    fn commit(&self, hash: &str) -> String {
+        match &self.commit_url {
+            Some(template) => format!("[{}]({})", hash, template.replace("{hash}", hash)),
+            None => hash.to_string(),
+        }
+    }
+}
+
+/// Normalize the `origin` remote URL to an `https://host/owner/repo` base,
+/// accepting `https://host/owner/repo.git`, `ssh://git@host:port/owner/repo`
+/// and the scp-like `git@host:owner/repo.git`.
+fn derive_base_url(repo: &git2::Repository) -> Option<String> {
+    let remote = repo.find_remote("origin").ok()?;
+    let url = remote.url()?.trim_end_matches(".git").to_string();
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Some(url)
+    } else if let Some(after_scheme) = url.splitn(2, "://").nth(1) {
+        // ssh:// or git:// URL: drop any user@ prefix and explicit :port.
+        let host_path = after_scheme.splitn(2, '@').last().unwrap_or(after_scheme);
+        let cleaned = match host_path.find('/') {
+            Some(slash) => {
+                let (host, path) = host_path.split_at(slash);
+                format!("{}{}", host.split(':').next().unwrap_or(host), path)
+            }
+            None => host_path.to_string(),
+        };
+        Some(format!("https://{}", cleaned))
+    } else if let Some(rest) = url.splitn(2, '@').nth(1) {
+        // scp-like syntax: git@host:owner/repo
+        Some(format!("https://{}", rest.replacen(':', "/", 1)))
     } else {
-        String::new()
-    };
-    let headline = headline_parts.join(":").trim().to_string();
-
-    let mut result = match &commit_type.to_lowercase()[..] {
-        "feat" | "feature" => Report {
-            header: headline,
-            commit_type: FEAT_TYPE,
-            description: None,
-            context: context,
-            related_issues: Vec::new(),
-            solved_issues: Vec::new(),
-            breaking_changes: Vec::new(),
-        },
-        "fix" => Report {
-            header: headline,
-            commit_type: FIX_TYPE,
-            description: None,
-            context: context,
-            related_issues: Vec::new(),
-            solved_issues: Vec::new(),
-            breaking_changes: Vec::new(),
-        },
-        _ => return None,
+        None
+    }
+}
+
+fn load_config(
+    repo_root: &std::path::Path,
+    override_path: Option<std::path::PathBuf>,
+) -> Result<Config, Box<std::error::Error>> {
+    let path = override_path.unwrap_or_else(|| repo_root.join(".changelog.toml"));
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    let raw = std::fs::read_to_string(path)?;
+    Ok(toml::from_str(&raw)?)
+}
+fn parse_report(raw_input: &str) -> Option<Report> {
+    lazy_static! {
+        static ref HEADER: Regex =
+            Regex::new(r"^(?P<type>\w+)(\((?P<scope>[^)]+)\))?(?P<bang>!)?: (?P<desc>.+)$")
+                .expect("unable to parse header regex");
+        static ref FOOTER: Regex =
+            Regex::new(r"^(?P<token>BREAKING CHANGE|[\w-]+)(?P<sep>: | #)(?P<value>.+)$")
+                .expect("unable to parse footer regex");
+    }
+
+    let mut lines = raw_input.lines();
+    let caps = HEADER.captures(lines.next()?)?;
+
+    let context = caps
+        .name("scope")
+        .map(|s| s.as_str().to_string())
+        .unwrap_or_default();
+    let header = caps["desc"].trim().to_string();
+
+    let mut result = Report {
+        header: header.clone(),
+        commit_type: CommitType::new(&caps["type"]),
+        description: None,
+        context: context,
+        release: Release::unreleased(),
+        hash: String::new(),
+        related_issues: Vec::new(),
+        solved_issues: Vec::new(),
+        breaking_changes: Vec::new(),
+        components: Vec::new(),
     };
 
-    for mut part in split {
-        part = part.trim();
-        if part == "" {
-            continue;
-        }
-        if part.to_lowercase().starts_with("solves:\n") {
-            result.solved_issues = parse_array(part);
-        } else if part.to_lowercase().starts_with("related:\n") {
-            result.related_issues = parse_array(part);
-        } else if part.to_lowercase().starts_with("breaking_changes:\n")
-            || part.to_lowercase().starts_with("breaking changes:\n")
+    // Split the remaining lines into a body and a trailing footer. The footer
+    // is the contiguous block of `Token: value` / `Token #value` lines at the
+    // very end of the message.
+    let rest: Vec<&str> = lines.collect();
+    let mut footer_start = rest.len();
+    for i in (0..rest.len()).rev() {
+        let line = rest[i].trim();
+        if line.is_empty() {
+            // Skip the trailing newline(s) git keeps on the message, but stop
+            // once the blank line that separates body from footer is reached.
+            if footer_start == rest.len() {
+                continue;
+            }
+            break;
+        }
+        if FOOTER.is_match(line) {
+            footer_start = i;
+        } else {
+            break;
+        }
+    }
+
+    let body = rest[..footer_start].join("\n");
+    let body = body.trim();
+    if !body.is_empty() {
+        result.description = Some(body.to_string());
+    }
+
+    for line in &rest[footer_start..] {
+        let footer = match FOOTER.captures(line.trim()) {
+            Some(c) => c,
+            None => continue,
+        };
+        let value = footer["value"].trim().to_string();
+        let token = &footer["token"];
+        if token.eq_ignore_ascii_case("breaking change")
+            || token.eq_ignore_ascii_case("breaking-change")
         {
-            result.breaking_changes = parse_array(part);
+            result.breaking_changes.push(value);
         } else {
-            result.description = Some(part.to_string());
+            match &token.to_lowercase()[..] {
+                "closes" | "fixes" | "resolves" => result.solved_issues.push(value),
+                "refs" | "related" => result.related_issues.push(value),
+                _ => {}
+            }
         }
     }
 
+    if caps.name("bang").is_some() && result.breaking_changes.is_empty() {
+        result.breaking_changes.push(header);
+    }
+
     Some(result)
 }
 
-fn find_latest_tag_commit_id(
+/// All tags of the repository paired with the commit they point at, sorted
+/// oldest-first by commit time. This is the basis both for finding the latest
+/// release and for walking the full release history.
+fn collect_tag_commits(
     repo: &git2::Repository,
-) -> Result<Option<git2::Oid>, Box<std::error::Error>> {
+) -> Result<Vec<(String, git2::Commit)>, Box<std::error::Error>> {
     let tags = repo.tag_names(None)?;
-    let mut reports: Vec<git2::Commit> = tags
+    let mut reports: Vec<(String, git2::Commit)> = tags
         .iter()
-        .filter(|possible_tag| possible_tag.is_some())
-        .map(|t| t.unwrap())
+        .filter_map(|possible_tag| possible_tag)
         .filter_map(|raw_tag| {
             repo.revparse_single(raw_tag)
-                .expect("unable to find reference for tag")
-                .peel_to_commit()
                 .ok()
+                .and_then(|obj| obj.peel_to_commit().ok())
+                .map(|commit| (raw_tag.to_string(), commit))
         })
         .collect();
-    reports.sort_by(|a, b| a.time().seconds().cmp(&b.time().seconds()));
+    reports.sort_by(|a, b| a.1.time().seconds().cmp(&b.1.time().seconds()));
+    Ok(reports)
+}
+
+fn find_latest_tag_commit_id(
+    repo: &git2::Repository,
+) -> Result<Option<git2::Oid>, Box<std::error::Error>> {
+    Ok(collect_tag_commits(repo)?
+        .last()
+        .map(|(_, commit)| commit.id()))
+}
+
+/// The release a commit belongs to: a tag name and its date, or the empty tag
+/// used for the unreleased delta. `order` is an oldest-first index used to emit
+/// releases newest-first in the changelog.
+#[derive(Clone)]
+struct Release {
+    tag: String,
+    date: String,
+    order: usize,
+}
+
+impl Release {
+    /// The implicit release holding commits that are not part of any tag.
+    fn unreleased() -> Release {
+        Release {
+            tag: String::new(),
+            date: String::new(),
+            order: 0,
+        }
+    }
+}
+
+/// Collect the commits of the unreleased delta (default mode) or an explicit
+/// revision range, all attributed to a single unnamed release.
+fn collect_unreleased_revs(
+    repo: &git2::Repository,
+    args: &[String],
+) -> Result<Vec<(git2::Oid, Release)>, Box<std::error::Error>> {
+    let mut revwalk = repo.revwalk()?;
+    if args.is_empty() {
+        match find_latest_tag_commit_id(repo)? {
+            Some(id) => {
+                revwalk.hide(id)?;
+                revwalk.push_head()?;
+            }
+            None => return Ok(Vec::new()),
+        }
+    } else {
+        let range = &args[0];
+        if !range.contains("..") {
+            revwalk.push(repo.revparse_single(range)?.id())?;
+        } else {
+            revwalk.push_range(&range[..])?;
+        }
+    }
+
+    let release = Release::unreleased();
+    Ok(revwalk
+        .filter_map(|item| item.ok())
+        .map(|oid| (oid, release.clone()))
+        .collect())
+}
+
+/// Walk the full history tag by tag, attributing every commit to the release it
+/// was first tagged in and anything past the most recent tag to `Unreleased`.
+fn collect_release_revs(
+    repo: &git2::Repository,
+) -> Result<Vec<(git2::Oid, Release)>, Box<std::error::Error>> {
+    let tags = collect_tag_commits(repo)?;
+    let mut revs = Vec::new();
+    // Commits already attributed to an earlier release. Hiding all of them —
+    // not just the immediately preceding tag — keeps a commit from appearing
+    // twice when tags are not laid out linearly in time (e.g. a hotfix tagged
+    // off an older base).
+    let mut seen: Vec<git2::Oid> = Vec::new();
+
+    for (order, (name, commit)) in tags.iter().enumerate() {
+        let release = Release {
+            tag: name.clone(),
+            date: format_date(commit.time()),
+            order,
+        };
+        revs.extend(
+            walk_hiding(repo, &seen, commit.id())?
+                .into_iter()
+                .map(|oid| (oid, release.clone())),
+        );
+        seen.push(commit.id());
+    }
+
+    let head = repo.head()?.peel_to_commit()?.id();
+    let unreleased = walk_hiding(repo, &seen, head)?;
+    if !unreleased.is_empty() {
+        let release = Release {
+            tag: "Unreleased".to_string(),
+            date: String::new(),
+            order: tags.len(),
+        };
+        revs.extend(unreleased.into_iter().map(|oid| (oid, release.clone())));
+    }
+
+    Ok(revs)
+}
 
-    let possible_latest_tag = reports.last();
-    if possible_latest_tag.is_none() {
-        return Ok(None);
+/// The commits reachable from `to` but from none of the `hidden` commits.
+fn walk_hiding(
+    repo: &git2::Repository,
+    hidden: &[git2::Oid],
+    to: git2::Oid,
+) -> Result<Vec<git2::Oid>, Box<std::error::Error>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(to)?;
+    for oid in hidden {
+        revwalk.hide(*oid)?;
     }
-    let latest_tag = possible_latest_tag.unwrap();
-    Ok(Some(latest_tag.id()))
+    Ok(revwalk.filter_map(|item| item.ok()).collect())
+}
+
+/// Format a commit timestamp as a `YYYY-MM-DD` calendar date using Howard
+/// Hinnant's civil-from-days algorithm, so no date-handling dependency is
+/// needed just to stamp a release heading.
+fn format_date(time: git2::Time) -> String {
+    let secs = time.seconds() + i64::from(time.offset_minutes()) * 60;
+    let days = secs.div_euclid(86_400);
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let year = yoe + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = if month <= 2 { year + 1 } else { year };
+    format!("{:04}-{:02}-{:02}", year, month, day)
 }
 
 struct Worker {
-    oid_receiver: crossbeam_channel::Receiver<git2::Oid>,
+    oid_receiver: crossbeam_channel::Receiver<(git2::Oid, Release)>,
     report_sender: crossbeam_channel::Sender<Report>,
     repo: git2::Repository,
+    components: ComponentTrie,
 }
 
 impl Worker {
@@ -181,126 +562,337 @@ impl Worker {
                 drop(self.report_sender);
                 return;
             }
-            let oid = possible_oid.unwrap();
-            let result = self.process_commit(oid);
+            let (oid, release) = possible_oid.unwrap();
+            let result = self.process_commit(oid, release);
             if result.is_err() {
                 panic!("error while commit lookup: {}", result.err().unwrap());
             }
         }
     }
 
-    fn process_commit(&self, oid: git2::Oid) -> Result<(), Box<std::error::Error>> {
+    fn process_commit(
+        &self,
+        oid: git2::Oid,
+        release: Release,
+    ) -> Result<(), Box<std::error::Error>> {
         let commit = self.repo.find_commit(oid)?;
         let message = commit.message().unwrap_or("");
         if message == "" {
             return Ok(());
         }
-        let possible_report = parse_report(message);
-        if possible_report.is_some() {
-            self.report_sender.send(possible_report.unwrap())?;
+        if let Some(mut report) = parse_report(message) {
+            report.release = release;
+            report.hash = commit
+                .as_object()
+                .short_id()
+                .ok()
+                .and_then(|buf| buf.as_str().map(|s| s.to_string()))
+                .unwrap_or_else(|| oid.to_string());
+            if !self.components.is_empty() {
+                report.components = self.matching_components(&commit)?;
+            }
+            self.report_sender.send(report)?;
         }
         Ok(())
     }
+
+    /// The components a commit touches, found by diffing it against its first
+    /// parent and resolving every changed path through the component trie.
+    fn matching_components(
+        &self,
+        commit: &git2::Commit,
+    ) -> Result<Vec<String>, Box<std::error::Error>> {
+        let tree = commit.tree()?;
+        let parent_tree = if commit.parent_count() > 0 {
+            Some(commit.parent(0)?.tree()?)
+        } else {
+            None
+        };
+        let diff = self
+            .repo
+            .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+
+        let mut matched = std::collections::BTreeSet::new();
+        for delta in diff.deltas() {
+            let path = delta
+                .new_file()
+                .path()
+                .or_else(|| delta.old_file().path());
+            if let Some(path) = path {
+                if let Some(name) = self.components.match_path(&path.to_string_lossy()) {
+                    matched.insert(name.to_string());
+                }
+            }
+        }
+        Ok(matched.into_iter().collect())
+    }
 }
 
-fn parse_array(input: &str) -> Vec<String> {
-    lazy_static! {
-        static ref CLEANER: Regex = Regex::new(r"\s+-\s+").expect("unable to parse array regex");
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+enum CommitType {
+    Feature,
+    Fix,
+    Perf,
+    Refactor,
+    Docs,
+    Build,
+    Ci,
+    Test,
+    Chore,
+    Other(String),
+}
+
+impl CommitType {
+    fn new(raw: &str) -> CommitType {
+        match &raw.to_lowercase()[..] {
+            "feat" | "feature" => CommitType::Feature,
+            "fix" => CommitType::Fix,
+            "perf" => CommitType::Perf,
+            "refactor" => CommitType::Refactor,
+            "docs" => CommitType::Docs,
+            "build" => CommitType::Build,
+            "ci" => CommitType::Ci,
+            "test" => CommitType::Test,
+            "chore" => CommitType::Chore,
+            other => CommitType::Other(other.to_string()),
+        }
     }
-    CLEANER
-        .split(input)
-        .skip(1)
-        .map(|i| i.to_string())
-        .collect()
 }
 
+#[derive(Clone)]
 struct Report {
     header: String,
     description: Option<String>,
     context: String,
-    commit_type: usize,
+    commit_type: CommitType,
+    release: Release,
+    hash: String,
     related_issues: Vec<String>,
     solved_issues: Vec<String>,
     breaking_changes: Vec<String>,
+    /// Components this commit was attributed to (monorepo mode). Empty when no
+    /// components are configured, in which case the report lands in the single
+    /// top-level changelog.
+    components: Vec<String>,
 }
 
 impl Report {
-    fn print(&self, mut out: impl std::io::Write) -> std::io::Result<()> {
-        writeln!(&mut out, "{}\n", self.header)?;
-        if self.description.is_some() {
-            writeln!(&mut out, "{}", self.description.clone().unwrap())?;
+    /// Flatten a report into its renderable form, pre-applying the link
+    /// templates so templates only deal with plain strings.
+    fn to_context(&self, links: &Links) -> ReportContext {
+        ReportContext {
+            header: self.header.clone(),
+            description: self.description.clone(),
+            has_hash: !self.hash.is_empty(),
+            hash: if self.hash.is_empty() {
+                String::new()
+            } else {
+                links.commit(&self.hash)
+            },
+            solved_issues: self.solved_issues.iter().map(|i| links.issue(i)).collect(),
+            related_issues: self
+                .related_issues
+                .iter()
+                .map(|i| links.issue(i))
+                .collect(),
         }
-        Ok(())
     }
 }
 
-struct ReportAggregator {
-    reports: BTreeMap<String, [Vec<Report>; 2]>,
+/// The reports of a single release: the per-context commit buckets plus the
+/// breaking changes collected for that release.
+struct ReleaseBucket {
+    release: Release,
+    reports: BTreeMap<String, BTreeMap<CommitType, Vec<Report>>>,
     breaking_changes: Vec<String>,
 }
 
+impl ReleaseBucket {
+    fn new(release: Release) -> Self {
+        ReleaseBucket {
+            release,
+            reports: BTreeMap::new(),
+            breaking_changes: Vec::new(),
+        }
+    }
+
+    /// Shape this release into the serializable form handed to the template.
+    fn to_context(&self, config: &Config, links: &Links) -> ReleaseContext {
+        let mut contexts = Vec::new();
+        for (name, type_map) in &self.reports {
+            let sections: Vec<SectionContext> = config
+                .sections
+                .iter()
+                .filter(|s| !s.hidden)
+                .filter_map(|s| {
+                    type_map.get(&CommitType::new(&s.commit_type)).map(|reports| {
+                        SectionContext {
+                            title: s.title.clone(),
+                            reports: reports.iter().map(|r| r.to_context(links)).collect(),
+                        }
+                    })
+                })
+                .collect();
+
+            if sections.is_empty() {
+                continue;
+            }
+
+            contexts.push(ContextContext {
+                name: name.clone(),
+                is_general: name.is_empty(),
+                sections,
+            });
+        }
+
+        ReleaseContext {
+            has_tag: !self.release.tag.is_empty(),
+            has_date: !self.release.date.is_empty(),
+            tag: self.release.tag.clone(),
+            date: self.release.date.clone(),
+            contexts,
+            breaking_changes: self.breaking_changes.clone(),
+        }
+    }
+}
+
+/// The full, serializable changelog handed to the templating engine.
+#[derive(Serialize)]
+struct ChangelogContext {
+    releases: Vec<ReleaseContext>,
+}
+
+#[derive(Serialize)]
+struct ReleaseContext {
+    tag: String,
+    date: String,
+    has_tag: bool,
+    has_date: bool,
+    contexts: Vec<ContextContext>,
+    breaking_changes: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ContextContext {
+    name: String,
+    is_general: bool,
+    sections: Vec<SectionContext>,
+}
+
+#[derive(Serialize)]
+struct SectionContext {
+    title: String,
+    reports: Vec<ReportContext>,
+}
+
+#[derive(Serialize)]
+struct ReportContext {
+    header: String,
+    description: Option<String>,
+    hash: String,
+    has_hash: bool,
+    solved_issues: Vec<String>,
+    related_issues: Vec<String>,
+}
+
+struct ReportAggregator {
+    /// Per-component release buckets, keyed by component name. The empty key
+    /// holds the single top-level changelog used when no components are
+    /// configured.
+    components: BTreeMap<String, BTreeMap<usize, ReleaseBucket>>,
+}
+
 impl ReportAggregator {
     fn new() -> Self {
         ReportAggregator {
-            reports: BTreeMap::new(),
-            breaking_changes: Vec::new(),
+            components: BTreeMap::new(),
         }
     }
 
     fn add_report(&mut self, report: Report) {
+        // A commit touching several components appears in each of them; one
+        // with no component lands in the top-level changelog under the empty
+        // key.
+        if report.components.is_empty() {
+            self.insert_into(String::new(), report);
+        } else {
+            for component in report.components.clone() {
+                self.insert_into(component, report.clone());
+            }
+        }
+    }
+
+    fn insert_into(&mut self, component: String, report: Report) {
+        let releases = self
+            .components
+            .entry(component)
+            .or_insert_with(BTreeMap::new);
+        let bucket = releases
+            .entry(report.release.order)
+            .or_insert_with(|| ReleaseBucket::new(report.release.clone()));
         for bc in &report.breaking_changes {
-            self.breaking_changes.push(bc.clone());
+            bucket.breaking_changes.push(bc.clone());
         }
-        self.reports
+        bucket
+            .reports
             .entry(report.context.clone())
-            .or_insert([Vec::new(), Vec::new()])[report.commit_type]
+            .or_insert_with(BTreeMap::new)
+            .entry(report.commit_type.clone())
+            .or_insert_with(Vec::new)
             .push(report);
     }
 
-    fn print(&self, mut out: impl std::io::Write) -> std::io::Result<()> {
-        for (k, v) in &self.reports {
-            if v[FIX_TYPE].len() > 0 || v[FEAT_TYPE].len() > 0 {
-                if k != "" {
-                    writeln!(&mut out, "### {}\n", k)?;
-                }
-            }
-
-            if v[FEAT_TYPE].len() > 0 {
-                if k == "" {
-                    writeln!(out, "### General Features\n")?;
-                } else {
-                    writeln!(out, "#### Features\n")?;
-                }
-                for report in &v[FEAT_TYPE] {
-                    report.print(&mut out)?;
-                }
-                writeln!(out)?;
-            }
-
-            if v[FIX_TYPE].len() > 0 {
-                if k == "" {
-                    writeln!(out, "### General Fixes\n")?;
-                } else {
-                    writeln!(out, "#### Fixes\n")?;
-                }
-                for report in &v[FIX_TYPE] {
-                    report.print(&mut out)?;
-                }
-                writeln!(out)?;
-            }
-        }
-        if self.breaking_changes.len() > 0 {
-            writeln!(out, "### BREAKING CHANGES\n")?;
-            for bc in &self.breaking_changes {
-                writeln!(out, "{}\n", bc)?;
-            }
+    fn build_context(&self, component: &str, config: &Config, links: &Links) -> ChangelogContext {
+        let releases = match self.components.get(component) {
+            Some(releases) => releases,
+            None => return ChangelogContext { releases: Vec::new() },
+        };
+        ChangelogContext {
+            releases: releases
+                .values()
+                .rev()
+                .map(|bucket| bucket.to_context(config, links))
+                .collect(),
         }
+    }
+
+    fn render(
+        &self,
+        component: &str,
+        config: &Config,
+        links: &Links,
+        template: &str,
+        out: impl std::io::Write,
+    ) -> Result<(), Box<std::error::Error>> {
+        let context = self.build_context(component, config, links);
+        let mut handlebars = handlebars::Handlebars::new();
+        handlebars.register_escape_fn(handlebars::no_escape);
+        handlebars.render_template_to_write(template, &context, out)?;
         Ok(())
     }
 }
 
-const FIX_TYPE: usize = 1;
-const FEAT_TYPE: usize = 0;
+/// The built-in Markdown layout, used unless `--template` points at another
+/// file. Every template line is joined with `\` so only the explicit `\n`
+/// escapes reach the output, keeping the spacing under tight control.
+const DEFAULT_TEMPLATE: &str = "\
+{{#each releases}}\
+{{#if has_tag}}## {{tag}}{{#if has_date}} ({{date}}){{/if}}\n\n{{/if}}\
+{{#each contexts}}\
+{{#unless is_general}}### {{name}}\n\n{{/unless}}\
+{{#each sections}}\
+{{#if ../is_general}}### General {{title}}\n\n{{else}}#### {{title}}\n\n{{/if}}\
+{{#each reports}}\
+{{header}}{{#if has_hash}} ({{hash}}){{/if}}\n\n\
+{{#if description}}{{description}}\n{{/if}}\
+{{#each solved_issues}}- closes {{this}}\n{{/each}}\
+{{#each related_issues}}- related {{this}}\n{{/each}}\
+{{/each}}\
+\n{{/each}}\
+{{/each}}\
+{{#if breaking_changes}}### BREAKING CHANGES\n\n\
+{{#each breaking_changes}}{{this}}\n\n{{/each}}{{/if}}\
+{{/each}}";
 
 #[cfg(test)]
 mod tests {
@@ -318,28 +910,37 @@ mod tests {
                     .to_string(),
             ),
             context: "cmd/update".to_string(),
-            commit_type: FEAT_TYPE,
+            commit_type: CommitType::Feature,
+            release: Release::unreleased(),
+            hash: String::new(),
             related_issues: vec!["foo".to_string(), "bar".to_string()],
             solved_issues: vec!["hallo".to_string(), "welt".to_string()],
             breaking_changes: vec!["bla".to_string(), "blubb".to_string()],
+            components: vec![],
         },
         Report {
             header: "Some fix".to_string(),
             description:None,
             context: String::new(),
-            commit_type: FIX_TYPE,
+            commit_type: CommitType::Fix,
+            release: Release::unreleased(),
+            hash: String::new(),
             related_issues: vec![],
             solved_issues: vec![],
             breaking_changes: vec![],
+            components: vec![],
         },
          Report {
             header: "Fix something".to_string(),
             description:None,
             context: String::new(),
-            commit_type: FIX_TYPE,
+            commit_type: CommitType::Fix,
+            release: Release::unreleased(),
+            hash: String::new(),
             related_issues: vec![],
             solved_issues: vec![],
             breaking_changes: vec!["break something".to_string(),"break some real long thing\nthat wraps arround two lines".to_string()],
+            components: vec![],
         },
         ];
 
@@ -368,19 +969,24 @@ mod tests {
         for rep in test_table {
             aggregator.add_report(rep);
         }
+        let config = Config::default();
+        let links = Links {
+            issue_url: None,
+            commit_url: None,
+        };
         let mut change_log_path = test_assets_path.clone();
         change_log_path.push("change_logs/1.txt");
         if update_golden.is_ok() {
             let f =
                 std::fs::File::create(&change_log_path).expect("unable to create change logs file");
 
-            let result = aggregator.print(f);
+            let result = aggregator.render("", &config, &links, DEFAULT_TEMPLATE, f);
             assert!(result.is_ok());
             return;
         }
 
         let mut output = Vec::new();
-        let result = aggregator.print(&mut output);
+        let result = aggregator.render("", &config, &links, DEFAULT_TEMPLATE, &mut output);
         match result {
             Ok(_) => {}
             Err(e) => {